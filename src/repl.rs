@@ -0,0 +1,218 @@
+use lalrpop_util::ParseError;
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+
+use crate::grammar;
+use crate::semic::ast::{CExpr, CFunc, CProto, CStmt, CType, ReplInput};
+use crate::semic::diag::{Diagnostic, Diagnostics};
+use crate::semic::env::{FuncTab, SymTab};
+use crate::semic::interp::Session;
+use crate::semic::typecheck;
+
+// interactive REPL
+//
+// Reads one logical input at a time (a declaration, prototype, function,
+// statement, or bare expression), parsing it with `grammar::ReplParser`
+// against the accumulated input buffer. An unrecognized-EOF is treated as
+// "give me another line" so multi-line `if`/`while`/function bodies can be
+// typed across several prompts; any other parse error is reported and the
+// buffer is reset.
+//
+// Every successfully parsed chunk other than a `Proto` (which is only
+// registered) is type-checked — `Decl`/`Stmt` chunks
+// through `typecheck::check_stmt_repl`, `Func` chunks through
+// `typecheck::check_func`, bare `Expr` chunks through `typecheck::infer` —
+// against REPL-local function/symbol tables that accumulate the same way
+// `typecheck`'s own tables do over a whole `CProg`. Diagnostics of
+// `Decl`/`Stmt`/`Func` chunks are always printed (an ill-typed bare `Expr`
+// gets a single generic error); a chunk with a fatal error is never handed to the interpreter,
+// so a mistyped declaration or function body can't reach `Session` and
+// mis-execute. Redeclaring a name is allowed (a REPL needs to let you
+// redefine things) but prints a warning rather than silently shadowing.
+//
+// Line editing and history are handled by `rustyline`, persisted to
+// `HISTORY_FILE` across sessions.
+//
+// The parser borrows identifiers out of the source text (`CIdent<'input>
+// = &'input str`), so every accumulated line has to outlive the `Session`
+// that ends up holding its declarations. We sidestep the issue by leaking
+// each completed chunk into a `&'static str` with `Box::leak` and running
+// the whole REPL at `'static` — a small, deliberate trade of memory for a
+// process that only ever grows its source, never frees it.
+
+const HISTORY_FILE: &str = ".semic_history";
+
+pub fn run() {
+    let mut session: Session<'static> = Session::new();
+    let mut funcs: FuncTab<'static, &'static CProto<'static>> = FuncTab::new();
+    funcs.push_frame();
+    let mut syms: SymTab<'static, CType> = SymTab::new();
+    syms.push_frame();
+
+    let mut rl: Editor<()> = Editor::new();
+    let _ = rl.load_history(HISTORY_FILE);
+
+    let mut buf = String::new();
+
+    loop {
+        let prompt = if buf.is_empty() { "semic> " } else { "...... " };
+        let line = match rl.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                eprintln!("readline error: {}", err);
+                break;
+            },
+        };
+        rl.add_history_entry(line.as_str());
+
+        if buf.is_empty() {
+            match line.trim() {
+                ":quit" | ":q" => break,
+                cmd if cmd.starts_with(":type ") => {
+                    print_type(&cmd[":type ".len()..], &funcs, &mut syms);
+                    continue;
+                },
+                _ => (),
+            }
+        }
+
+        buf.push_str(&line);
+        buf.push('\n');
+        let source: &'static str = leak(&buf);
+
+        match grammar::ReplParser::new().parse(source) {
+            Ok(input) => {
+                buf.clear();
+                handle_input(input, source, &mut session, &mut funcs, &mut syms);
+            },
+            Err(ParseError::UnrecognizedEOF { .. }) => {
+                // Incomplete input — keep the buffer and prompt for more.
+            },
+            Err(err) => {
+                eprintln!("parse error: {:?}", err);
+                buf.clear();
+            },
+        }
+    }
+
+    let _ = rl.save_history(HISTORY_FILE);
+}
+
+fn leak(s: &str) -> &'static str {
+    Box::leak(s.to_string().into_boxed_str())
+}
+
+fn report_diags(diags: &Diagnostics<'_>) {
+    for d in diags.iter() {
+        eprintln!("{}", d);
+    }
+}
+
+/// Warns (rather than errors) that `name` already had a binding — a REPL
+/// needs to allow redefining things, so this is advisory only.
+fn warn_redecl(name: &str, source: &'static str) {
+    eprintln!("{}", Diagnostic::warning((0, 0), format!("`{}` redefined", name), source));
+}
+
+fn handle_input(
+    input: ReplInput<'static>,
+    source: &'static str,
+    session: &mut Session<'static>,
+    funcs: &mut FuncTab<'static, &'static CProto<'static>>,
+    syms: &mut SymTab<'static, CType>,
+) {
+    match input {
+        ReplInput::Decl(ty, name, init) => {
+            let stmt = CStmt::Decl((0, 0), ty.clone(), name, init);
+            let stmt: &'static CStmt<'static> = Box::leak(Box::new(stmt));
+            if syms.lookup(name).is_some() {
+                warn_redecl(name, source);
+            }
+
+            let diags = typecheck::check_stmt_repl(stmt, funcs, syms, source);
+            report_diags(&diags);
+            if diags.is_fatal() {
+                return;
+            }
+
+            if let CStmt::Decl(_, _, _, ref init) = *stmt {
+                if let Err(err) = session.define_var(&ty, name, init.as_ref()) {
+                    eprintln!("{}", err);
+                }
+            }
+        },
+
+        ReplInput::Proto(proto) => {
+            let proto: &'static CProto<'static> = Box::leak(Box::new(proto));
+            if let Ok(Some(_)) = funcs.insert(&proto.name, proto) {
+                warn_redecl(&proto.name, source);
+            }
+        },
+
+        ReplInput::Func(func) => {
+            let func: &'static CFunc<'static> = Box::leak(Box::new(func));
+            if let Ok(Some(_)) = funcs.insert(&func.proto.name, &func.proto) {
+                warn_redecl(&func.proto.name, source);
+            }
+
+            let diags = typecheck::check_func(func, funcs, source);
+            report_diags(&diags);
+            if diags.is_fatal() {
+                return;
+            }
+
+            session.define_func(func);
+        },
+
+        ReplInput::Stmt(stmt) => {
+            let stmt: &'static CStmt<'static> = Box::leak(Box::new(stmt));
+
+            let diags = typecheck::check_stmt_repl(stmt, funcs, syms, source);
+            report_diags(&diags);
+            if diags.is_fatal() {
+                return;
+            }
+
+            if let Err(err) = session.eval_stmt(stmt) {
+                eprintln!("{}", err);
+            }
+        },
+
+        ReplInput::Expr(expr) => {
+            let expr: &'static CExpr<'static> = Box::leak(Box::new(expr));
+            match typecheck::infer(expr, funcs, syms) {
+                // Printing a bare expression is just `printf(expr)` with
+                // the checker already having confirmed it type-checks, so we
+                // reuse the interpreter's own `Print` handling rather than
+                // duplicating the eval-and-format logic here.
+                Some(_ty) => {
+                    let print_stmt: &'static CStmt<'static> =
+                        Box::leak(Box::new(CStmt::Print((0, 0), None, expr.clone())));
+                    if let Err(err) = session.eval_stmt(print_stmt) {
+                        eprintln!("{}", err);
+                    }
+                },
+                None => eprintln!("{}", Diagnostic::error((0, 0), "ill-typed expression".to_string(), source)),
+            }
+        },
+    }
+}
+
+fn print_type(
+    src: &str,
+    funcs: &FuncTab<'static, &'static CProto<'static>>,
+    syms: &mut SymTab<'static, CType>,
+) {
+    let source = leak(src);
+    match grammar::ExprParser::new().parse(source) {
+        Ok(expr) => {
+            let expr: &'static CExpr<'static> = Box::leak(Box::new(expr));
+            match typecheck::infer(expr, funcs, syms) {
+                Some(ty) => println!("{:?}", ty),
+                None => eprintln!("ill-typed expression"),
+            }
+        },
+        Err(err) => eprintln!("parse error: {:?}", err),
+    }
+}
@@ -1,5 +1,5 @@
 use std::str::Chars;
-use std::fmt::{Debug, Formatter, Error};
+use std::fmt::{Debug, Display, Formatter, Error};
 
 
 pub type CProg<'input> = Vec<CProgElem<'input>>;
@@ -12,6 +12,18 @@ pub enum CProgElem<'input> {
     Error,
 }
 
+/// One parsed chunk of REPL input: either a top-level declaration (handled
+/// the same as in a whole `CProg`) or a bare statement/expression typed at
+/// the prompt, which `CProg` itself has no room for.
+#[derive(Clone)]
+pub enum ReplInput<'input> {
+    Decl(CType, CIdent<'input>, Option<CExpr<'input>>),
+    Proto(CProto<'input>),
+    Func(CFunc<'input>),
+    Stmt(CStmt<'input>),
+    Expr(CExpr<'input>),
+}
+
 #[derive(Clone, Debug)]
 pub struct CProto<'input> {
     pub ret: Option<CType>,
@@ -123,8 +135,8 @@ impl<'input> Debug for CStmt<'input> {
             },
             Call(_, ref i, ref p) => {
                 let mut s: String = String::new();
-                for (i, e) in p.iter().enumerate() {
-                    if i > 0 { s.push_str(", ") }
+                for (n, e) in p.iter().enumerate() {
+                    if n > 0 { s.push_str(", ") }
                     s.push_str(&format!("{:?}", e));
                 }
                 write!(fmt, "{}({})", i, s)
@@ -164,8 +176,8 @@ impl<'input> Debug for CExpr<'input> {
             BinOp(_, op, ref l, ref r) => write!(fmt, "({:?} {:?} {:?})", l, op, r),
             Call(_, ref i, ref p) => {
                 let mut s: String = String::new();
-                for (i, e) in p.iter().enumerate() {
-                    if i > 0 { s.push_str(", ") }
+                for (n, e) in p.iter().enumerate() {
+                    if n > 0 { s.push_str(", ") }
                     s.push_str(&format!("{:?}", e));
                 }
                 write!(fmt, "{}({})", i, s)
@@ -211,3 +223,240 @@ impl Debug for CType {
         }
     }
 }
+
+
+// display trait (pretty-printing)
+//
+// Unlike the `Debug` impls above (compact, meant for diagnostics and
+// `-vv` style dumps), these reconstruct something close to the original
+// source: proper indentation, braces on the same line as `if`/`while`,
+// and parentheses only where precedence actually requires them.
+
+const INDENT: &str = "    ";
+
+fn pad(indent: usize) -> String {
+    INDENT.repeat(indent)
+}
+
+/// Binding power of a binary/unary operator; higher binds tighter. Used to
+/// decide whether a child `BinOp` needs parenthesizing.
+fn prec(op: COp) -> u8 {
+    use self::COp::*;
+    match op {
+        Or => 1,
+        And => 2,
+        Eq | Neq => 3,
+        Lt | Lte | Gt | Gte => 4,
+        Add | Sub => 5,
+        Mul | Div => 6,
+        Neg | Not => 7,
+    }
+}
+
+impl Display for COp {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        use self::COp::*;
+        match *self {
+            Mul => write!(fmt, "*"),
+            Div => write!(fmt, "/"),
+            Add => write!(fmt, "+"),
+            Sub => write!(fmt, "-"),
+            Eq  => write!(fmt, "=="),
+            Neq => write!(fmt, "!="),
+            Lt  => write!(fmt, "<"),
+            Lte => write!(fmt, "<="),
+            Gt  => write!(fmt, ">"),
+            Gte => write!(fmt, ">="),
+            And => write!(fmt, "&&"),
+            Or  => write!(fmt, "||"),
+            Neg => write!(fmt, "-"),
+            Not => write!(fmt, "!"),
+        }
+    }
+}
+
+impl Display for CType {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        use self::CType::*;
+        match *self {
+            Char => write!(fmt, "char"),
+            Int => write!(fmt, "int"),
+            Float => write!(fmt, "float"),
+            Ref(ref t) => write!(fmt, "{}*", t),
+        }
+    }
+}
+
+impl<'input> Display for CExpr<'input> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        fmt_expr(self, fmt, 0)
+    }
+}
+
+/// Writes `expr`, wrapping it in parentheses if its own precedence is lower
+/// than `min_prec` (the precedence the surrounding expression requires).
+fn fmt_expr(expr: &CExpr, fmt: &mut Formatter, min_prec: u8) -> Result<(), Error> {
+    use self::CExpr::*;
+    match *expr {
+        Int(_, i) => write!(fmt, "{}", i),
+        Float(_, f) => write!(fmt, "{:?}", f),
+        Str(_, ref s) => write!(fmt, "\"{}\"", s.as_str()),
+        Char(_, c) => write!(fmt, "'{}'", c),
+        Ident(_, name) => write!(fmt, "{}", name),
+
+        UnOp(_, op, ref e) => {
+            write!(fmt, "{}", op)?;
+            fmt_expr(e, fmt, prec(COp::Neg))
+        },
+
+        BinOp(_, op, ref l, ref r) => {
+            let p = prec(op);
+            let parens = p < min_prec;
+            if parens { write!(fmt, "(")?; }
+            fmt_expr(l, fmt, p)?;
+            write!(fmt, " {} ", op)?;
+            fmt_expr(r, fmt, p + 1)?;
+            if parens { write!(fmt, ")")?; }
+            Ok(())
+        },
+
+        Call(_, name, ref args) => {
+            write!(fmt, "{}(", name)?;
+            for (i, a) in args.iter().enumerate() {
+                if i > 0 { write!(fmt, ", ")?; }
+                write!(fmt, "{}", a)?;
+            }
+            write!(fmt, ")")
+        },
+
+        Index(_, name, ref idx) => write!(fmt, "{}[{}]", name, idx),
+
+        Error => write!(fmt, "<error>"),
+    }
+}
+
+impl<'input> Display for CStmt<'input> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        fmt_stmt(self, fmt, 0)
+    }
+}
+
+/// Writes `stmt` at `indent` levels deep, without a leading pad (the caller
+/// positions the cursor) and without a trailing newline.
+fn fmt_stmt(stmt: &CStmt, fmt: &mut Formatter, indent: usize) -> Result<(), Error> {
+    use self::CStmt::*;
+    match *stmt {
+        Decl(_, ref ty, name, ref init) => match *init {
+            Some(ref e) => write!(fmt, "{} {} = {};", ty, name, e),
+            None => write!(fmt, "{} {};", ty, name),
+        },
+
+        Assign(_, name, ref index, ref rhs) => match *index {
+            Some(ref idx) => write!(fmt, "{}[{}] = {};", name, idx, rhs),
+            None => write!(fmt, "{} = {};", name, rhs),
+        },
+
+        Call(_, name, ref args) => {
+            write!(fmt, "{}(", name)?;
+            for (i, a) in args.iter().enumerate() {
+                if i > 0 { write!(fmt, ", ")?; }
+                write!(fmt, "{}", a)?;
+            }
+            write!(fmt, ");")
+        },
+
+        Return(_, ref val) => match *val {
+            Some(ref e) => write!(fmt, "return {};", e),
+            None => write!(fmt, "return;"),
+        },
+
+        Block(_, ref stmts) => {
+            writeln!(fmt, "{{")?;
+            for s in stmts {
+                write!(fmt, "{}", pad(indent + 1))?;
+                fmt_stmt(s, fmt, indent + 1)?;
+                writeln!(fmt)?;
+            }
+            write!(fmt, "{}}}", pad(indent))
+        },
+
+        If(_, ref cond, ref then_branch, ref else_branch) => {
+            write!(fmt, "if ({})", cond)?;
+            fmt_branch(then_branch, fmt, indent)?;
+            if let Some(ref else_branch) = *else_branch {
+                write!(fmt, " else")?;
+                fmt_branch(else_branch, fmt, indent)?;
+            }
+            Ok(())
+        },
+
+        While(_, ref cond, ref body) => {
+            write!(fmt, "while ({})", cond)?;
+            fmt_branch(body, fmt, indent)
+        },
+
+        Print(_, ref format, ref e) => match *format {
+            Some(ref s) => write!(fmt, "printf(\"{}\", {});", s.as_str(), e),
+            None => write!(fmt, "printf({});", e),
+        },
+
+        Error => write!(fmt, "<error>;"),
+    }
+}
+
+/// Writes the body of an `if`/`while`: ` { ... }` on the same line when it's
+/// already a `Block`, otherwise an indented statement on the line below.
+fn fmt_branch(stmt: &CStmt, fmt: &mut Formatter, indent: usize) -> Result<(), Error> {
+    match *stmt {
+        CStmt::Block(..) => {
+            write!(fmt, " ")?;
+            fmt_stmt(stmt, fmt, indent)
+        },
+        _ => {
+            writeln!(fmt)?;
+            write!(fmt, "{}", pad(indent + 1))?;
+            fmt_stmt(stmt, fmt, indent + 1)
+        },
+    }
+}
+
+impl<'input> Display for CProto<'input> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        match self.ret {
+            Some(ref t) => write!(fmt, "{} {}(", t, self.name)?,
+            None => write!(fmt, "void {}(", self.name)?,
+        }
+        for (i, &(ref ty, name)) in self.params.iter().enumerate() {
+            if i > 0 { write!(fmt, ", ")?; }
+            write!(fmt, "{} {}", ty, name)?;
+        }
+        write!(fmt, ")")
+    }
+}
+
+impl<'input> Display for CFunc<'input> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        write!(fmt, "{} ", self.proto)?;
+        fmt_stmt(&self.body, fmt, 0)
+    }
+}
+
+impl<'input> Display for CProgElem<'input> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        match *self {
+            CProgElem::Decl(_, ref ty, name, ref init) => match *init {
+                Some(ref e) => write!(fmt, "{} {} = {};", ty, name, e),
+                None => write!(fmt, "{} {};", ty, name),
+            },
+            CProgElem::Proto(_, ref proto) => write!(fmt, "{};", proto),
+            CProgElem::Func(_, ref func) => write!(fmt, "{}", func),
+            CProgElem::Error => write!(fmt, "<error>;"),
+        }
+    }
+}
+
+/// Renders a whole program back to (re-parseable, modulo whitespace)
+/// source text, each top-level element followed by a newline.
+pub fn pretty_print(ast: &CProg) -> String {
+    ast.iter().map(|elem| format!("{}\n", elem)).collect()
+}
@@ -0,0 +1,118 @@
+use super::ast::*;
+use super::diag::{Diagnostic, Diagnostics};
+use super::env::{FuncTab, SymTab};
+
+// checker functions
+
+type VarEntry = (CType, CLoc);
+type FuncEntry<'input> = (&'input CProto<'input>, Option<&'input CFunc<'input>>);
+
+pub fn check_prog<'input>(ast: &'input CProg<'input>, source: &'input str) -> Diagnostics<'input> {
+    let mut diags = Diagnostics::new();
+
+    // global function table
+    let mut funcs: FuncTab<'input, FuncEntry<'input>> = FuncTab::new();
+    funcs.push_frame();
+    // symbol table
+    let mut syms: SymTab<'input, VarEntry> = SymTab::new();
+    syms.push_frame();
+
+    // check each element
+    for elem in ast.iter() {
+        match *elem {
+            CProgElem::Decl(loc, ref ty, name, _) => {
+                match syms.insert(name, (ty.clone(), loc)) {
+                    Ok(Some((_, prev_loc))) => {
+                        diags.push(Diagnostic::hint(
+                            prev_loc,
+                            format!("`{}` previously declared here", name),
+                            source,
+                        ));
+                        diags.push(Diagnostic::error(
+                            loc,
+                            format!("variable `{}` already declared", name),
+                            source,
+                        ));
+                    },
+                    Ok(None) => (),
+                    Err(_) => diags.push(Diagnostic::error(
+                        loc,
+                        "internal error: symbol table has no open scope".to_string(),
+                        source,
+                    )),
+                };
+            },
+
+            CProgElem::Func(loc, ref func) => {
+                let CFunc { ref proto, .. } = *func;
+                let CProto { ref name, .. } = *proto;
+
+                match funcs.insert(name, (proto, Some(func))) {
+                    Ok(Some(x)) => match x {
+                        (_, None) => (),
+                        (prev_proto, Some(_)) => {
+                            diags.push(Diagnostic::hint(
+                                proto_loc(ast, prev_proto),
+                                format!("`{}` previously defined here", name),
+                                source,
+                            ));
+                            diags.push(Diagnostic::error(
+                                loc,
+                                format!("function `{}` already declared", name),
+                                source,
+                            ));
+                        },
+                    },
+                    Ok(None) => (),
+                    Err(_) => diags.push(Diagnostic::error(
+                        loc,
+                        "internal error: function table has no open scope".to_string(),
+                        source,
+                    )),
+                };
+            },
+
+            CProgElem::Proto(loc, ref proto) => {
+                let CProto { ref name, .. } = *proto;
+
+                match funcs.insert(name, (proto, None)) {
+                    Ok(Some((prev_proto, _))) => {
+                        diags.push(Diagnostic::hint(
+                            proto_loc(ast, prev_proto),
+                            format!("`{}` previously declared here", name),
+                            source,
+                        ));
+                        diags.push(Diagnostic::error(
+                            loc,
+                            format!("function `{}` already defined", name),
+                            source,
+                        ));
+                    },
+                    Ok(None) => (),
+                    Err(_) => diags.push(Diagnostic::error(
+                        loc,
+                        "internal error: function table has no open scope".to_string(),
+                        source,
+                    )),
+                };
+            },
+
+            CProgElem::Error => (),
+        };
+    }
+
+    diags
+}
+
+/// Finds the `CLoc` of the program element that owns `proto`, so a
+/// redeclaration diagnostic can point back at the original declaration site.
+fn proto_loc<'input>(ast: &'input CProg<'input>, proto: &'input CProto<'input>) -> CLoc {
+    for elem in ast.iter() {
+        match *elem {
+            CProgElem::Proto(loc, ref p) if std::ptr::eq(p, proto) => return loc,
+            CProgElem::Func(loc, ref f) if std::ptr::eq(&f.proto, proto) => return loc,
+            _ => (),
+        }
+    }
+    (0, 0)
+}
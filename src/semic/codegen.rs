@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+
+use super::ast::*;
+use super::env::SymTab;
+use super::vm::{Instr, Program, Section};
+
+// bytecode compiler
+//
+// Lowers a checked `CProg` into a `vm::Program`: one labeled `Section` per
+// `CFunc`, plus a `globals` instruction stream that evaluates every
+// top-level `CProgElem::Decl`'s initializer and stores it to a dedicated
+// global slot (assigned up front, same as a `CFunc`'s params get local
+// slots). Locals are addressed by a frame offset assigned as declarations
+// are walked (reusing `SymTab` for name resolution, same as the checker and
+// interpreter); `If`/`While` reserve a branch instruction and back-patch its
+// target once the jump destination is known.
+
+pub fn compile_prog<'input>(ast: &'input CProg<'input>) -> Program {
+    let mut section_names: Vec<String> = Vec::new();
+    let mut ret_types: HashMap<String, Option<CType>> = HashMap::new();
+    let mut param_types: HashMap<String, Vec<CType>> = HashMap::new();
+    let mut global_slots: HashMap<String, usize> = HashMap::new();
+    let mut global_types: HashMap<String, CType> = HashMap::new();
+
+    for elem in ast.iter() {
+        match *elem {
+            CProgElem::Func(_, ref func) => {
+                section_names.push(func.proto.name.to_string());
+                ret_types.insert(func.proto.name.to_string(), func.proto.ret.clone());
+                param_types.insert(
+                    func.proto.name.to_string(),
+                    func.proto.params.iter().map(|&(ref ty, _)| ty.clone()).collect(),
+                );
+            },
+            CProgElem::Proto(_, ref proto) => {
+                ret_types.insert(proto.name.to_string(), proto.ret.clone());
+                param_types.insert(
+                    proto.name.to_string(),
+                    proto.params.iter().map(|&(ref ty, _)| ty.clone()).collect(),
+                );
+            },
+            CProgElem::Decl(_, ref ty, name, _) => {
+                let slot = global_slots.len();
+                global_slots.insert(name.to_string(), slot);
+                global_types.insert(name.to_string(), ty.clone());
+            },
+            CProgElem::Error => (),
+        }
+    }
+
+    let sections = ast
+        .iter()
+        .filter_map(|elem| match *elem {
+            CProgElem::Func(_, ref func) => Some(compile_func(
+                func,
+                &section_names,
+                &ret_types,
+                &param_types,
+                &global_slots,
+                &global_types,
+            )),
+            _ => None,
+        })
+        .collect();
+
+    let globals =
+        compile_globals(ast, &section_names, &ret_types, &param_types, &global_slots, &global_types);
+
+    Program { sections, global_count: global_slots.len(), globals }
+}
+
+/// Compiles every top-level `CProgElem::Decl`'s initializer (or `0`) into a
+/// `StoreGlobal`, run once before the VM's entry section. Uses a
+/// `FuncCompiler` with an empty local scope — globals can reference
+/// functions and earlier globals, just like ordinary statements, just
+/// never locals (there are none at this scope).
+fn compile_globals<'input>(
+    ast: &'input CProg<'input>,
+    section_names: &[String],
+    ret_types: &HashMap<String, Option<CType>>,
+    param_types: &HashMap<String, Vec<CType>>,
+    global_slots: &HashMap<String, usize>,
+    global_types: &HashMap<String, CType>,
+) -> Vec<Instr> {
+    let mut c = FuncCompiler {
+        slots: SymTab::new(),
+        types: SymTab::new(),
+        next_slot: 0,
+        instrs: Vec::new(),
+        section_names,
+        ret_types,
+        param_types,
+        global_slots,
+        global_types,
+    };
+    c.slots.push_frame();
+    c.types.push_frame();
+
+    for elem in ast.iter() {
+        if let CProgElem::Decl(_, ref ty, name, ref init) = *elem {
+            match *init {
+                Some(ref e) => c.compile_expr(e),
+                None => c.instrs.push(Instr::PushInt(0)),
+            }
+            if let Some(instr) = coerce_instr(ty) {
+                c.instrs.push(instr);
+            }
+            let slot = *global_slots.get(name).expect("global was assigned a slot above");
+            c.instrs.push(Instr::StoreGlobal(slot));
+        }
+    }
+
+    c.instrs.push(Instr::PushInt(0));
+    c.instrs.push(Instr::Ret);
+    c.instrs
+}
+
+fn compile_func<'input, 'a>(
+    func: &'input CFunc<'input>,
+    section_names: &'a [String],
+    ret_types: &'a HashMap<String, Option<CType>>,
+    param_types: &'a HashMap<String, Vec<CType>>,
+    global_slots: &'a HashMap<String, usize>,
+    global_types: &'a HashMap<String, CType>,
+) -> Section {
+    let mut c = FuncCompiler {
+        slots: SymTab::new(),
+        types: SymTab::new(),
+        next_slot: 0,
+        instrs: Vec::new(),
+        section_names,
+        ret_types,
+        param_types,
+        global_slots,
+        global_types,
+    };
+
+    c.slots.push_frame();
+    c.types.push_frame();
+    for &(ref ty, name) in &func.proto.params {
+        c.declare(name, ty.clone());
+    }
+
+    c.compile_stmt(&func.body);
+    // Every section falls through to a `Ret`, so a void function (or one
+    // that runs off the end of its body) still leaves something to pop.
+    c.instrs.push(Instr::PushInt(0));
+    c.instrs.push(Instr::Ret);
+
+    Section { name: func.proto.name.to_string(), params: func.proto.params.len(), instrs: c.instrs }
+}
+
+struct FuncCompiler<'input, 'a> {
+    slots: SymTab<'input, usize>,
+    types: SymTab<'input, CType>,
+    next_slot: usize,
+    instrs: Vec<Instr>,
+    section_names: &'a [String],
+    ret_types: &'a HashMap<String, Option<CType>>,
+    param_types: &'a HashMap<String, Vec<CType>>,
+    global_slots: &'a HashMap<String, usize>,
+    global_types: &'a HashMap<String, CType>,
+}
+
+impl<'input, 'a> FuncCompiler<'input, 'a> {
+    fn declare(&mut self, name: &'input str, ty: CType) -> usize {
+        let slot = self.next_slot;
+        self.next_slot += 1;
+        let _ = self.slots.insert(name, slot);
+        let _ = self.types.insert(name, ty);
+        slot
+    }
+
+    /// `Load`/`Store` a local when `name` is one (locals shadow globals,
+    /// same as in the tree-walking interpreter's nested scopes), otherwise
+    /// `LoadGlobal`/`StoreGlobal` the slot reserved for it in `compile_prog`.
+    fn load_instr(&self, name: &str) -> Instr {
+        match self.slots.lookup(name) {
+            Some(&slot) => Instr::Load(slot),
+            None => match self.global_slots.get(name) {
+                Some(&slot) => Instr::LoadGlobal(slot),
+                None => unreachable!("unresolved identifier made it past the type checker"),
+            },
+        }
+    }
+
+    fn store_instr(&self, name: &str) -> Instr {
+        match self.slots.lookup(name) {
+            Some(&slot) => Instr::Store(slot),
+            None => match self.global_slots.get(name) {
+                Some(&slot) => Instr::StoreGlobal(slot),
+                None => unreachable!("unresolved identifier made it past the type checker"),
+            },
+        }
+    }
+
+    /// Best-effort static type of `expr`, used only to pick the right typed
+    /// arithmetic instruction; unresolvable cases default to `Int`.
+    fn static_type(&self, expr: &CExpr) -> CType {
+        match *expr {
+            CExpr::Int(..) => CType::Int,
+            CExpr::Float(..) => CType::Float,
+            CExpr::Char(..) => CType::Char,
+            CExpr::Str(..) => CType::Ref(Box::new(CType::Char)),
+            CExpr::Ident(_, name) => self
+                .types
+                .lookup(name)
+                .or_else(|| self.global_types.get(name))
+                .cloned()
+                .unwrap_or(CType::Int),
+            CExpr::UnOp(_, _, ref e) => self.static_type(e),
+            CExpr::BinOp(_, op, ref l, ref r) => match op {
+                COp::Mul | COp::Div | COp::Add | COp::Sub => {
+                    let (lt, rt) = (self.static_type(l), self.static_type(r));
+                    if lt == CType::Float || rt == CType::Float {
+                        CType::Float
+                    } else if lt == CType::Char && rt == CType::Char {
+                        CType::Char
+                    } else {
+                        CType::Int
+                    }
+                },
+                _ => CType::Int,
+            },
+            CExpr::Index(_, name, _) => match self.types.lookup(name).or_else(|| self.global_types.get(name)) {
+                Some(CType::Ref(ref t)) => (**t).clone(),
+                _ => CType::Int,
+            },
+            CExpr::Call(_, name, _) => self.ret_types.get(name).cloned().flatten().unwrap_or(CType::Int),
+            CExpr::Error => CType::Int,
+        }
+    }
+
+    fn compile_stmt(&mut self, stmt: &'input CStmt<'input>) {
+        match *stmt {
+            CStmt::Decl(_, ref ty, name, ref init) => {
+                match *init {
+                    Some(ref e) => self.compile_expr(e),
+                    None => self.instrs.push(Instr::PushInt(0)),
+                }
+                if let Some(instr) = coerce_instr(ty) {
+                    self.instrs.push(instr);
+                }
+                let slot = self.declare(name, ty.clone());
+                self.instrs.push(Instr::Store(slot));
+            },
+
+            CStmt::Assign(_, name, ref index, ref rhs) => match *index {
+                Some(ref idx) => {
+                    self.instrs.push(self.load_instr(name));
+                    self.compile_expr(idx);
+                    self.compile_expr(rhs);
+                    self.instrs.push(Instr::StoreIndex);
+                },
+                None => {
+                    self.compile_expr(rhs);
+                    if let Some(ty) = self.types.lookup(name).or_else(|| self.global_types.get(name)) {
+                        if let Some(instr) = coerce_instr(ty) {
+                            self.instrs.push(instr);
+                        }
+                    }
+                    self.instrs.push(self.store_instr(name));
+                },
+            },
+
+            CStmt::Call(_, name, ref args) => {
+                self.compile_call(name, args);
+                self.instrs.push(Instr::Pop);
+            },
+
+            CStmt::Return(_, ref val) => {
+                match *val {
+                    Some(ref e) => self.compile_expr(e),
+                    None => self.instrs.push(Instr::PushInt(0)),
+                }
+                self.instrs.push(Instr::Ret);
+            },
+
+            CStmt::Block(_, ref stmts) => {
+                self.slots.push_frame();
+                self.types.push_frame();
+                for s in stmts {
+                    self.compile_stmt(s);
+                }
+                self.types.pop_frame();
+                self.slots.pop_frame();
+            },
+
+            CStmt::If(_, ref cond, ref then_branch, ref else_branch) => {
+                self.compile_expr(cond);
+                let jump_unless = self.emit_jump_unless();
+                self.compile_stmt(then_branch);
+                match *else_branch {
+                    Some(ref else_branch) => {
+                        let jump_end = self.emit_jump();
+                        self.patch_jump(jump_unless);
+                        self.compile_stmt(else_branch);
+                        self.patch_jump(jump_end);
+                    },
+                    None => self.patch_jump(jump_unless),
+                }
+            },
+
+            CStmt::While(_, ref cond, ref body) => {
+                let loop_start = self.instrs.len();
+                self.compile_expr(cond);
+                let jump_end = self.emit_jump_unless();
+                self.compile_stmt(body);
+                self.instrs.push(Instr::Jump(loop_start));
+                self.patch_jump(jump_end);
+            },
+
+            CStmt::Print(_, ref format, ref e) => {
+                match *format {
+                    Some(ref s) => self.instrs.push(Instr::PushStr(s.as_str().to_string())),
+                    None => self.instrs.push(Instr::PushStr(String::new())),
+                }
+                self.compile_expr(e);
+                self.instrs.push(Instr::ExternBuiltin("printf".to_string()));
+                self.instrs.push(Instr::Pop);
+            },
+
+            CStmt::Error => (),
+        }
+    }
+
+    fn compile_expr(&mut self, expr: &'input CExpr<'input>) {
+        match *expr {
+            CExpr::Int(_, n) => self.instrs.push(Instr::PushInt(n)),
+            CExpr::Float(_, f) => self.instrs.push(Instr::PushFloat(f)),
+            CExpr::Char(_, c) => self.instrs.push(Instr::PushInt(c as i32)),
+            CExpr::Str(_, ref s) => self.instrs.push(Instr::PushStr(s.as_str().to_string())),
+            CExpr::Ident(_, name) => self.instrs.push(self.load_instr(name)),
+
+            CExpr::UnOp(_, op, ref e) => {
+                self.compile_expr(e);
+                self.instrs.push(match op {
+                    COp::Neg => Instr::Neg,
+                    COp::Not => Instr::Not,
+                    _ => unreachable!("not a unary operator"),
+                });
+            },
+
+            CExpr::BinOp(_, op, ref l, ref r) => {
+                let is_float = self.static_type(l) == CType::Float || self.static_type(r) == CType::Float;
+                self.compile_expr(l);
+                self.compile_expr(r);
+                self.instrs.push(binop_instr(op, is_float));
+            },
+
+            CExpr::Index(_, name, ref index) => {
+                self.instrs.push(self.load_instr(name));
+                self.compile_expr(index);
+                self.instrs.push(Instr::Index);
+            },
+
+            CExpr::Call(_, name, ref args) => self.compile_call(name, args),
+
+            CExpr::Error => self.instrs.push(Instr::PushInt(0)),
+        }
+    }
+
+    fn compile_call(&mut self, name: &'input str, args: &'input [Box<CExpr<'input>>]) {
+        let params = self.param_types.get(name);
+        for (i, arg) in args.iter().enumerate() {
+            self.compile_expr(arg);
+            // Builtins (no entry in param_types) take whatever's on the
+            // stack as-is — e.g. printf's varargs have no declared type.
+            if let Some(instr) = params.and_then(|p| p.get(i)).and_then(coerce_instr) {
+                self.instrs.push(instr);
+            }
+        }
+        match self.section_names.iter().position(|n| n == name) {
+            Some(addr) => self.instrs.push(Instr::Call(addr)),
+            None => self.instrs.push(Instr::ExternBuiltin(name.to_string())),
+        }
+    }
+
+    fn emit_jump_unless(&mut self) -> usize {
+        self.instrs.push(Instr::JumpUnless(0));
+        self.instrs.len() - 1
+    }
+
+    fn emit_jump(&mut self) -> usize {
+        self.instrs.push(Instr::Jump(0));
+        self.instrs.len() - 1
+    }
+
+    /// Back-patches the branch reserved at `idx` to target the next
+    /// instruction to be emitted.
+    fn patch_jump(&mut self, idx: usize) {
+        let target = self.instrs.len();
+        match self.instrs[idx] {
+            Instr::Jump(ref mut t) => *t = target,
+            Instr::JumpUnless(ref mut t) => *t = target,
+            _ => unreachable!("patch_jump on a non-jump instruction"),
+        }
+    }
+}
+
+/// The conversion to run right before storing into (or binding a param of)
+/// a slot declared `ty`, so e.g. a float-typed slot always holds a
+/// `Value::Float` even when its initializer/argument evaluated to an int.
+/// `Ref` slots (arrays) are never implicitly converted.
+fn coerce_instr(ty: &CType) -> Option<Instr> {
+    match *ty {
+        CType::Int => Some(Instr::CoerceInt),
+        CType::Float => Some(Instr::CoerceFloat),
+        CType::Char => Some(Instr::CoerceChar),
+        CType::Ref(_) => None,
+    }
+}
+
+fn binop_instr(op: COp, is_float: bool) -> Instr {
+    match op {
+        COp::Add => if is_float { Instr::AddFloat } else { Instr::AddInt },
+        COp::Sub => if is_float { Instr::SubFloat } else { Instr::SubInt },
+        COp::Mul => if is_float { Instr::MulFloat } else { Instr::MulInt },
+        COp::Div => if is_float { Instr::DivFloat } else { Instr::DivInt },
+        COp::Eq => Instr::CmpEq,
+        COp::Neq => Instr::CmpNeq,
+        COp::Lt => Instr::CmpLt,
+        COp::Lte => Instr::CmpLte,
+        COp::Gt => Instr::CmpGt,
+        COp::Gte => Instr::CmpGte,
+        COp::And => Instr::And,
+        COp::Or => Instr::Or,
+        COp::Neg | COp::Not => unreachable!("not a binary operator"),
+    }
+}
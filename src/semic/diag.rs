@@ -0,0 +1,148 @@
+use std::fmt;
+
+use super::ast::CLoc;
+
+/// How serious a diagnostic is. `Error` is fatal to the check; `Warning` and
+/// `Hint` are informational and never stop `check_prog` from otherwise
+/// completing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Hint,
+}
+
+impl Severity {
+    fn label(&self) -> &'static str {
+        match *self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Hint => "hint",
+        }
+    }
+
+    /// ANSI color code for the severity label (red/yellow/blue).
+    fn color(&self) -> &'static str {
+        match *self {
+            Severity::Error => "31",
+            Severity::Warning => "33",
+            Severity::Hint => "34",
+        }
+    }
+}
+
+/// A single source-span diagnostic: a location, a severity, a message, and a
+/// borrowed reference to the source the location is relative to, so it can
+/// render the offending line on demand.
+#[derive(Clone)]
+pub struct Diagnostic<'input> {
+    pub loc: CLoc,
+    pub severity: Severity,
+    pub message: String,
+    source: &'input str,
+}
+
+impl<'input> Diagnostic<'input> {
+    pub fn new(loc: CLoc, severity: Severity, message: String, source: &'input str) -> Diagnostic<'input> {
+        Diagnostic { loc, severity, message, source }
+    }
+
+    pub fn error(loc: CLoc, message: String, source: &'input str) -> Diagnostic<'input> {
+        Diagnostic::new(loc, Severity::Error, message, source)
+    }
+
+    pub fn warning(loc: CLoc, message: String, source: &'input str) -> Diagnostic<'input> {
+        Diagnostic::new(loc, Severity::Warning, message, source)
+    }
+
+    pub fn hint(loc: CLoc, message: String, source: &'input str) -> Diagnostic<'input> {
+        Diagnostic::new(loc, Severity::Hint, message, source)
+    }
+
+    /// Renders `severity: message`, the offending source line, and a `^`
+    /// caret underneath the column it points at.
+    pub fn render(&self) -> String {
+        let (line, col) = self.loc;
+
+        let mut out = format!(
+            "\x1b[{}m{}\x1b[0m: {} ({}:{})\n",
+            self.severity.color(),
+            self.severity.label(),
+            self.message,
+            line,
+            col,
+        );
+
+        if let Some(src_line) = self.source.lines().nth(line.saturating_sub(1)) {
+            out.push_str(src_line);
+            out.push('\n');
+
+            let pad: String = src_line
+                .chars()
+                .take(col.saturating_sub(1))
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            out.push_str(&pad);
+            out.push('^');
+        }
+
+        out
+    }
+}
+
+impl<'input> fmt::Display for Diagnostic<'input> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.render())
+    }
+}
+
+impl<'input> fmt::Debug for Diagnostic<'input> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{:?} {:?}: {}", self.severity, self.loc, self.message)
+    }
+}
+
+/// The result of checking a program: at most one terminating error (the
+/// first `Error` pushed, which makes the check fail) plus any number of
+/// notes collected along the way — hints, warnings, and any later errors.
+pub struct Diagnostics<'input> {
+    error: Option<Diagnostic<'input>>,
+    notes: Vec<Diagnostic<'input>>,
+}
+
+impl<'input> Diagnostics<'input> {
+    pub fn new() -> Diagnostics<'input> {
+        Diagnostics { error: None, notes: Vec::new() }
+    }
+
+    /// Records a diagnostic. The first `Error`-severity diagnostic becomes
+    /// the terminating error; any further ones are kept as notes so they're
+    /// still reported, just not treated as *the* failure.
+    pub fn push(&mut self, diag: Diagnostic<'input>) {
+        if diag.severity == Severity::Error && self.error.is_none() {
+            self.error = Some(diag);
+        } else {
+            self.notes.push(diag);
+        }
+    }
+
+    pub fn is_fatal(&self) -> bool {
+        self.error.is_some()
+    }
+
+    pub fn error(&self) -> Option<&Diagnostic<'input>> {
+        self.error.as_ref()
+    }
+
+    pub fn notes(&self) -> &[Diagnostic<'input>] {
+        &self.notes
+    }
+
+    /// All notes (in push order) followed by the terminating error, if any.
+    /// Notes exist to give context for the error — e.g. "previously
+    /// declared here" pointing back at an earlier site — so they read
+    /// before it, not after.
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic<'input>> {
+        self.notes.iter().chain(self.error.iter())
+    }
+}
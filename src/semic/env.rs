@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+
+/// A stack of lexical scopes mapping identifiers to a value of type `V`.
+///
+/// `push_frame`/`pop_frame` bracket a `Block`; lookups walk frames from the
+/// innermost outward so inner declarations shadow outer ones. `SymTab` and
+/// `FuncTab` are both instances of this, just keyed on different value types
+/// (variable info vs. function/prototype info).
+pub struct ScopeStack<'input, V> {
+    frames: Vec<HashMap<&'input str, V>>,
+}
+
+impl<'input, V> ScopeStack<'input, V> {
+    pub fn new() -> ScopeStack<'input, V> {
+        ScopeStack { frames: Vec::new() }
+    }
+
+    pub fn push_frame(&mut self) {
+        self.frames.push(HashMap::new());
+    }
+
+    pub fn pop_frame(&mut self) {
+        self.frames.pop();
+    }
+
+    /// Inserts `val` into the innermost frame, returning whatever it shadows
+    /// within that same frame (`None` if this is a fresh binding). Fails if
+    /// there is no open frame.
+    pub fn insert(&mut self, name: &'input str, val: V) -> Result<Option<V>, ()> {
+        match self.frames.last_mut() {
+            Some(frame) => Ok(frame.insert(name, val)),
+            None => Err(()),
+        }
+    }
+
+    /// Looks up `name` starting from the innermost frame outward.
+    pub fn lookup(&self, name: &str) -> Option<&V> {
+        self.frames.iter().rev().find_map(|frame| frame.get(name))
+    }
+
+    pub fn lookup_mut(&mut self, name: &str) -> Option<&mut V> {
+        self.frames.iter_mut().rev().find_map(|frame| frame.get_mut(name))
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+pub type SymTab<'input, V> = ScopeStack<'input, V>;
+pub type FuncTab<'input, V> = ScopeStack<'input, V>;
@@ -0,0 +1,536 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use super::ast::*;
+use super::env::{FuncTab, SymTab};
+
+// tree-walking interpreter
+//
+// Locates `main` in the function table and executes it directly against the
+// AST, as opposed to the linear bytecode backend. Arrays (`Ref` values) are
+// backed by a shared, growable `Vec` rather than a fixed-size buffer, since
+// nothing in the AST currently records an array's declared length.
+
+type Funcs<'input> = FuncTab<'input, &'input CFunc<'input>>;
+type Syms<'input> = SymTab<'input, Value>;
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Int(CInt),
+    Float(CFloat),
+    Char(CChar),
+    Ref(Rc<RefCell<Vec<Value>>>),
+}
+
+impl Value {
+    pub(crate) fn truthy(&self) -> bool {
+        match *self {
+            Value::Int(n) => n != 0,
+            Value::Float(f) => f != 0.0,
+            Value::Char(c) => c != '\0',
+            Value::Ref(_) => true,
+        }
+    }
+
+    pub(crate) fn as_i32(&self) -> i32 {
+        match *self {
+            Value::Int(n) => n,
+            Value::Float(f) => f as i32,
+            Value::Char(c) => c as i32,
+            Value::Ref(_) => 0,
+        }
+    }
+
+    pub(crate) fn as_f32(&self) -> f32 {
+        match *self {
+            Value::Int(n) => n as f32,
+            Value::Float(f) => f,
+            Value::Char(c) => (c as u32) as f32,
+            Value::Ref(_) => 0.0,
+        }
+    }
+
+    pub(crate) fn is_float(&self) -> bool {
+        match *self {
+            Value::Float(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Int(n) => write!(fmt, "{}", n),
+            Value::Float(f) => write!(fmt, "{}", f),
+            Value::Char(c) => write!(fmt, "{}", c),
+            Value::Ref(ref cells) => {
+                let s: String = cells.borrow().iter().map(|v| match *v {
+                    Value::Char(c) => c,
+                    _ => '?',
+                }).collect();
+                write!(fmt, "{}", s)
+            },
+        }
+    }
+}
+
+pub(crate) fn default_value(ty: &CType) -> Value {
+    match *ty {
+        CType::Int => Value::Int(0),
+        CType::Float => Value::Float(0.0),
+        CType::Char => Value::Char('\0'),
+        CType::Ref(_) => Value::Ref(Rc::new(RefCell::new(Vec::new()))),
+    }
+}
+
+/// Converts `val` to the representation its declared `CType` actually
+/// stores, so e.g. `float x = 7;` stores `Value::Float(7.0)` rather than
+/// `Value::Int(7)` and later arithmetic on `x` takes the float path.
+/// `Ref` values (arrays) are never implicitly converted between element
+/// types, so they pass through unchanged.
+pub(crate) fn coerce(ty: &CType, val: Value) -> Value {
+    match *ty {
+        CType::Int => Value::Int(val.as_i32()),
+        CType::Float => Value::Float(val.as_f32()),
+        CType::Char => Value::Char((val.as_i32() as u8) as char),
+        CType::Ref(_) => val,
+    }
+}
+
+#[derive(Debug)]
+pub enum RuntimeErrorKind {
+    DivisionByZero,
+    IndexOutOfBounds { index: i32, len: usize },
+    NotIndexable(String),
+    UndeclaredIdentifier(String),
+    UndefinedFunction(String),
+    MissingMain,
+}
+
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub loc: CLoc,
+    pub kind: RuntimeErrorKind,
+}
+
+impl RuntimeError {
+    fn new(loc: CLoc, kind: RuntimeErrorKind) -> RuntimeError {
+        RuntimeError { loc, kind }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let (line, col) = self.loc;
+        match self.kind {
+            RuntimeErrorKind::DivisionByZero =>
+                write!(fmt, "{}:{}: division by zero", line, col),
+            RuntimeErrorKind::IndexOutOfBounds { index, len } =>
+                write!(fmt, "{}:{}: index {} out of bounds (len {})", line, col, index, len),
+            RuntimeErrorKind::NotIndexable(ref name) =>
+                write!(fmt, "{}:{}: `{}` is not indexable", line, col, name),
+            RuntimeErrorKind::UndeclaredIdentifier(ref name) =>
+                write!(fmt, "{}:{}: undeclared identifier `{}`", line, col, name),
+            RuntimeErrorKind::UndefinedFunction(ref name) =>
+                write!(fmt, "{}:{}: call to undefined function `{}`", line, col, name),
+            RuntimeErrorKind::MissingMain => write!(fmt, "no `main` function defined"),
+        }
+    }
+}
+
+/// How a statement completed: either it ran normally, or it hit a `Return`
+/// that needs to unwind through any enclosing `Block`/`If`/`While`.
+pub enum Flow {
+    Normal,
+    Return(Option<Value>),
+}
+
+/// A function table and global scope that persist across successive
+/// top-level declarations and statements — what the REPL runs each parsed
+/// chunk against, as opposed to `run_prog`'s one-shot whole-program
+/// evaluation.
+pub struct Session<'input> {
+    funcs: Funcs<'input>,
+    globals: Syms<'input>,
+}
+
+impl<'input> Session<'input> {
+    pub fn new() -> Session<'input> {
+        let mut funcs: Funcs<'input> = FuncTab::new();
+        funcs.push_frame();
+        let mut globals: Syms<'input> = SymTab::new();
+        globals.push_frame();
+        Session { funcs, globals }
+    }
+
+    /// Registers a function definition so later calls can resolve it.
+    pub fn define_func(&mut self, func: &'input CFunc<'input>) {
+        let _ = self.funcs.insert(&func.proto.name, func);
+    }
+
+    /// Declares a global variable, evaluating its initializer (if any)
+    /// against the session's current globals.
+    pub fn define_var(
+        &mut self,
+        ty: &CType,
+        name: &'input str,
+        init: Option<&'input CExpr<'input>>,
+    ) -> Result<(), RuntimeError> {
+        let empty_locals: Syms<'input> = SymTab::new();
+        let val = match init {
+            Some(e) => coerce(ty, eval_expr(e, &self.funcs, &empty_locals, &mut self.globals)?),
+            None => default_value(ty),
+        };
+        let _ = self.globals.insert(name, val);
+        Ok(())
+    }
+
+    /// Executes a bare top-level statement (e.g. a call or a `printf`)
+    /// against the session's globals.
+    pub fn eval_stmt(&mut self, stmt: &'input CStmt<'input>) -> Result<Flow, RuntimeError> {
+        let mut locals: Syms<'input> = SymTab::new();
+        locals.push_frame();
+        exec_stmt(stmt, &self.funcs, &mut locals, &mut self.globals)
+    }
+}
+
+pub fn run_prog<'input>(ast: &'input CProg<'input>) -> Result<i32, RuntimeError> {
+    let mut funcs: Funcs<'input> = FuncTab::new();
+    funcs.push_frame();
+    let mut globals: Syms<'input> = SymTab::new();
+    globals.push_frame();
+
+    for elem in ast.iter() {
+        if let CProgElem::Func(_, ref func) = *elem {
+            let _ = funcs.insert(&func.proto.name, func);
+        }
+    }
+
+    let empty_locals: Syms<'input> = SymTab::new();
+    for elem in ast.iter() {
+        if let CProgElem::Decl(_, ref ty, name, ref init) = *elem {
+            let val = match *init {
+                Some(ref e) => coerce(ty, eval_expr(e, &funcs, &empty_locals, &mut globals)?),
+                None => default_value(ty),
+            };
+            let _ = globals.insert(name, val);
+        }
+    }
+
+    let main_func = *funcs
+        .lookup("main")
+        .ok_or_else(|| RuntimeError::new((0, 0), RuntimeErrorKind::MissingMain))?;
+
+    let result = call_func(main_func, Vec::new(), &funcs, &mut globals)?;
+    Ok(match result {
+        Some(ref v) => v.as_i32(),
+        None => 0,
+    })
+}
+
+fn lookup<'a, 'input>(locals: &'a Syms<'input>, globals: &'a Syms<'input>, name: &str) -> Option<&'a Value> {
+    locals.lookup(name).or_else(|| globals.lookup(name))
+}
+
+fn call_func<'input>(
+    func: &'input CFunc<'input>,
+    args: Vec<Value>,
+    funcs: &Funcs<'input>,
+    globals: &mut Syms<'input>,
+) -> Result<Option<Value>, RuntimeError> {
+    let mut locals: Syms<'input> = SymTab::new();
+    locals.push_frame();
+    for (val, &(ref ty, name)) in args.into_iter().zip(func.proto.params.iter()) {
+        let _ = locals.insert(name, coerce(ty, val));
+    }
+
+    match exec_stmt(&func.body, funcs, &mut locals, globals)? {
+        Flow::Return(v) => Ok(v),
+        Flow::Normal => Ok(None),
+    }
+}
+
+fn eval_call<'input>(
+    loc: CLoc,
+    name: CIdent<'input>,
+    args: &'input [Box<CExpr<'input>>],
+    funcs: &Funcs<'input>,
+    locals: &Syms<'input>,
+    globals: &mut Syms<'input>,
+) -> Result<Option<Value>, RuntimeError> {
+    let func = *funcs
+        .lookup(name)
+        .ok_or_else(|| RuntimeError::new(loc, RuntimeErrorKind::UndefinedFunction(name.to_string())))?;
+
+    let mut arg_vals = Vec::with_capacity(args.len());
+    for arg in args {
+        arg_vals.push(eval_expr(arg, funcs, locals, globals)?);
+    }
+
+    call_func(func, arg_vals, funcs, globals)
+}
+
+fn exec_stmt<'input>(
+    stmt: &'input CStmt<'input>,
+    funcs: &Funcs<'input>,
+    locals: &mut Syms<'input>,
+    globals: &mut Syms<'input>,
+) -> Result<Flow, RuntimeError> {
+    match *stmt {
+        CStmt::Decl(_, ref ty, name, ref init) => {
+            let val = match *init {
+                Some(ref e) => coerce(ty, eval_expr(e, funcs, locals, globals)?),
+                None => default_value(ty),
+            };
+            let _ = locals.insert(name, val);
+            Ok(Flow::Normal)
+        },
+
+        CStmt::Assign(loc, name, ref index, ref rhs) => {
+            let rhs_val = eval_expr(rhs, funcs, locals, globals)?;
+
+            match *index {
+                Some(ref idx_e) => {
+                    let idx = eval_expr(idx_e, funcs, locals, globals)?.as_i32();
+                    let arr = lookup(locals, globals, name)
+                        .ok_or_else(|| RuntimeError::new(loc, RuntimeErrorKind::UndeclaredIdentifier(name.to_string())))?;
+
+                    match *arr {
+                        Value::Ref(ref cells) => {
+                            if idx < 0 {
+                                return Err(RuntimeError::new(
+                                    loc,
+                                    RuntimeErrorKind::IndexOutOfBounds { index: idx, len: cells.borrow().len() },
+                                ));
+                            }
+                            let mut cells = cells.borrow_mut();
+                            let idx = idx as usize;
+                            if idx >= cells.len() {
+                                cells.resize(idx + 1, Value::Int(0));
+                            }
+                            cells[idx] = rhs_val;
+                        },
+                        _ => return Err(RuntimeError::new(loc, RuntimeErrorKind::NotIndexable(name.to_string()))),
+                    }
+                },
+                None => {
+                    let slot = locals
+                        .lookup_mut(name)
+                        .or_else(|| globals.lookup_mut(name))
+                        .ok_or_else(|| RuntimeError::new(loc, RuntimeErrorKind::UndeclaredIdentifier(name.to_string())))?;
+                    // Coerce to the variable's existing representation (its
+                    // declared type, set at Decl) rather than storing
+                    // whatever variant the rhs happened to evaluate to.
+                    *slot = match *slot {
+                        Value::Int(_) => Value::Int(rhs_val.as_i32()),
+                        Value::Float(_) => Value::Float(rhs_val.as_f32()),
+                        Value::Char(_) => Value::Char((rhs_val.as_i32() as u8) as char),
+                        Value::Ref(_) => rhs_val,
+                    };
+                },
+            }
+
+            Ok(Flow::Normal)
+        },
+
+        CStmt::Call(loc, name, ref args) => {
+            eval_call(loc, name, args, funcs, locals, globals)?;
+            Ok(Flow::Normal)
+        },
+
+        CStmt::Return(_, ref val) => {
+            let v = match *val {
+                Some(ref e) => Some(eval_expr(e, funcs, locals, globals)?),
+                None => None,
+            };
+            Ok(Flow::Return(v))
+        },
+
+        CStmt::Block(_, ref stmts) => {
+            locals.push_frame();
+            let mut flow = Flow::Normal;
+            for s in stmts {
+                flow = exec_stmt(s, funcs, locals, globals)?;
+                if let Flow::Return(_) = flow {
+                    break;
+                }
+            }
+            locals.pop_frame();
+            Ok(flow)
+        },
+
+        CStmt::If(_, ref cond, ref then_branch, ref else_branch) => {
+            let cond_val = eval_expr(cond, funcs, locals, globals)?;
+            if cond_val.truthy() {
+                exec_stmt(then_branch, funcs, locals, globals)
+            } else if let Some(ref else_branch) = *else_branch {
+                exec_stmt(else_branch, funcs, locals, globals)
+            } else {
+                Ok(Flow::Normal)
+            }
+        },
+
+        CStmt::While(_, ref cond, ref body) => {
+            loop {
+                let cond_val = eval_expr(cond, funcs, locals, globals)?;
+                if !cond_val.truthy() {
+                    break;
+                }
+                if let Flow::Return(v) = exec_stmt(body, funcs, locals, globals)? {
+                    return Ok(Flow::Return(v));
+                }
+            }
+            Ok(Flow::Normal)
+        },
+
+        CStmt::Print(_, ref format, ref e) => {
+            let val = eval_expr(e, funcs, locals, globals)?;
+            match *format {
+                Some(ref chars) => print!("{}", render_format(chars.as_str(), &val)),
+                None => println!("{}", val),
+            }
+            Ok(Flow::Normal)
+        },
+
+        CStmt::Error => Ok(Flow::Normal),
+    }
+}
+
+/// Interpolates `val` into `fmt` at the first `%d`/`%i`/`%f`/`%c`/`%s`
+/// placeholder found; if none is found, the value is just appended.
+pub(crate) fn render_format(format: &str, val: &Value) -> String {
+    let first = ["%d", "%i", "%f", "%c", "%s"]
+        .iter()
+        .filter_map(|spec| format.find(spec).map(|pos| (pos, spec.len())))
+        .min();
+    if let Some((pos, len)) = first {
+        let mut out = String::with_capacity(format.len());
+        out.push_str(&format[..pos]);
+        out.push_str(&val.to_string());
+        out.push_str(&format[pos + len..]);
+        return out;
+    }
+    format!("{}{}", format, val)
+}
+
+fn eval_expr<'input>(
+    expr: &'input CExpr<'input>,
+    funcs: &Funcs<'input>,
+    locals: &Syms<'input>,
+    globals: &mut Syms<'input>,
+) -> Result<Value, RuntimeError> {
+    match *expr {
+        CExpr::Int(_, n) => Ok(Value::Int(n)),
+        CExpr::Float(_, f) => Ok(Value::Float(f)),
+        CExpr::Char(_, c) => Ok(Value::Char(c)),
+        CExpr::Str(_, ref s) => {
+            let cells: Vec<Value> = s.as_str().chars().map(Value::Char).collect();
+            Ok(Value::Ref(Rc::new(RefCell::new(cells))))
+        },
+
+        CExpr::Ident(loc, name) => lookup(locals, globals, name)
+            .cloned()
+            .ok_or_else(|| RuntimeError::new(loc, RuntimeErrorKind::UndeclaredIdentifier(name.to_string()))),
+
+        CExpr::UnOp(_, op, ref e) => {
+            let val = eval_expr(e, funcs, locals, globals)?;
+            Ok(eval_unop(op, val))
+        },
+
+        CExpr::BinOp(loc, op, ref l, ref r) => {
+            let l_val = eval_expr(l, funcs, locals, globals)?;
+            let r_val = eval_expr(r, funcs, locals, globals)?;
+            eval_binop(loc, op, l_val, r_val)
+        },
+
+        CExpr::Index(loc, name, ref index) => {
+            let idx = eval_expr(index, funcs, locals, globals)?.as_i32();
+            let arr = lookup(locals, globals, name)
+                .ok_or_else(|| RuntimeError::new(loc, RuntimeErrorKind::UndeclaredIdentifier(name.to_string())))?;
+
+            match *arr {
+                Value::Ref(ref cells) => {
+                    let cells = cells.borrow();
+                    if idx < 0 || idx as usize >= cells.len() {
+                        return Err(RuntimeError::new(
+                            loc,
+                            RuntimeErrorKind::IndexOutOfBounds { index: idx, len: cells.len() },
+                        ));
+                    }
+                    Ok(cells[idx as usize].clone())
+                },
+                _ => Err(RuntimeError::new(loc, RuntimeErrorKind::NotIndexable(name.to_string()))),
+            }
+        },
+
+        CExpr::Call(loc, name, ref args) => {
+            Ok(eval_call(loc, name, args, funcs, locals, globals)?.unwrap_or(Value::Int(0)))
+        },
+
+        CExpr::Error => Ok(Value::Int(0)),
+    }
+}
+
+fn eval_unop(op: COp, val: Value) -> Value {
+    match op {
+        COp::Neg => match val {
+            Value::Int(n) => Value::Int(-n),
+            Value::Float(f) => Value::Float(-f),
+            Value::Char(c) => Value::Int(-(c as i32)),
+            Value::Ref(_) => Value::Int(0),
+        },
+        COp::Not => Value::Int(if val.truthy() { 0 } else { 1 }),
+        _ => unreachable!("not a unary operator"),
+    }
+}
+
+fn eval_binop(loc: CLoc, op: COp, l: Value, r: Value) -> Result<Value, RuntimeError> {
+    use self::COp::*;
+
+    match op {
+        Mul | Div | Add | Sub => {
+            if l.is_float() || r.is_float() {
+                let (a, b) = (l.as_f32(), r.as_f32());
+                let result = match op {
+                    Mul => a * b,
+                    Div => {
+                        if b == 0.0 {
+                            return Err(RuntimeError::new(loc, RuntimeErrorKind::DivisionByZero));
+                        }
+                        a / b
+                    },
+                    Add => a + b,
+                    Sub => a - b,
+                    _ => unreachable!(),
+                };
+                Ok(Value::Float(result))
+            } else {
+                let (a, b) = (l.as_i32(), r.as_i32());
+                let result = match op {
+                    Mul => a * b,
+                    Div => {
+                        if b == 0 {
+                            return Err(RuntimeError::new(loc, RuntimeErrorKind::DivisionByZero));
+                        }
+                        a / b
+                    },
+                    Add => a + b,
+                    Sub => a - b,
+                    _ => unreachable!(),
+                };
+                Ok(Value::Int(result))
+            }
+        },
+        Eq => Ok(Value::Int((l.as_f32() == r.as_f32()) as i32)),
+        Neq => Ok(Value::Int((l.as_f32() != r.as_f32()) as i32)),
+        Lt => Ok(Value::Int((l.as_f32() < r.as_f32()) as i32)),
+        Lte => Ok(Value::Int((l.as_f32() <= r.as_f32()) as i32)),
+        Gt => Ok(Value::Int((l.as_f32() > r.as_f32()) as i32)),
+        Gte => Ok(Value::Int((l.as_f32() >= r.as_f32()) as i32)),
+        And => Ok(Value::Int((l.truthy() && r.truthy()) as i32)),
+        Or => Ok(Value::Int((l.truthy() || r.truthy()) as i32)),
+        Neg | Not => unreachable!("not a binary operator"),
+    }
+}
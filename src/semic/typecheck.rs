@@ -0,0 +1,444 @@
+use super::ast::*;
+use super::diag::{Diagnostic, Diagnostics};
+use super::env::{FuncTab, SymTab};
+
+// type-checking pass
+//
+// Runs after `check_prog` has confirmed there are no redeclarations; walks
+// every function body computing a `CType` for each expression and checking
+// it against the rules laid out below. Errors are collected into the
+// returned `Diagnostics` rather than aborting the walk, so a single pass
+// reports as much as it can.
+
+pub(crate) type Funcs<'input> = FuncTab<'input, &'input CProto<'input>>;
+pub(crate) type Syms<'input> = SymTab<'input, CType>;
+
+pub fn typecheck<'input>(ast: &'input CProg<'input>, source: &'input str) -> Diagnostics<'input> {
+    let mut diags = Diagnostics::new();
+
+    let mut funcs: Funcs<'input> = FuncTab::new();
+    funcs.push_frame();
+    let mut syms: Syms<'input> = SymTab::new();
+    syms.push_frame();
+
+    for elem in ast.iter() {
+        match *elem {
+            CProgElem::Decl(_, ref ty, name, _) => {
+                let _ = syms.insert(name, ty.clone());
+            },
+            CProgElem::Proto(_, ref proto) => {
+                let _ = funcs.insert(&proto.name, proto);
+            },
+            CProgElem::Func(_, ref func) => {
+                let _ = funcs.insert(&func.proto.name, &func.proto);
+            },
+            CProgElem::Error => (),
+        }
+    }
+
+    for elem in ast.iter() {
+        if let CProgElem::Func(_, ref func) = *elem {
+            syms.push_frame();
+            for &(ref ty, name) in &func.proto.params {
+                let _ = syms.insert(name, ty.clone());
+            }
+            check_stmt(&func.body, &funcs, &mut syms, &func.proto.ret, source, &mut diags);
+            syms.pop_frame();
+        }
+    }
+
+    diags
+}
+
+/// Computes the type of a standalone expression (the REPL's `:type`
+/// meta-command) against caller-maintained function/symbol tables, silently
+/// discarding any diagnostics — `None` covers both "ill-typed" and
+/// "undeclared", which is all a REPL prompt needs to know.
+pub fn infer<'input>(
+    expr: &'input CExpr<'input>,
+    funcs: &Funcs<'input>,
+    syms: &mut Syms<'input>,
+) -> Option<CType> {
+    let mut diags = Diagnostics::new();
+    check_expr(expr, funcs, syms, "", &mut diags)
+}
+
+/// Type-checks one bare top-level statement (the REPL's non-`Func`,
+/// non-`Expr` chunks) against caller-maintained tables. There is no
+/// enclosing function, so a `return` with a value is reported exactly as
+/// it would be inside a `void` function.
+pub fn check_stmt_repl<'input>(
+    stmt: &'input CStmt<'input>,
+    funcs: &Funcs<'input>,
+    syms: &mut Syms<'input>,
+    source: &'input str,
+) -> Diagnostics<'input> {
+    let mut diags = Diagnostics::new();
+    check_stmt(stmt, funcs, syms, &None, source, &mut diags);
+    diags
+}
+
+/// Type-checks a whole function body against its own parameter scope,
+/// mirroring what `typecheck`'s main loop does for each `CProgElem::Func` —
+/// used by the REPL to check a function chunk before handing it to the
+/// interpreter.
+pub fn check_func<'input>(
+    func: &'input CFunc<'input>,
+    funcs: &Funcs<'input>,
+    source: &'input str,
+) -> Diagnostics<'input> {
+    let mut diags = Diagnostics::new();
+    let mut syms: Syms<'input> = SymTab::new();
+    syms.push_frame();
+    for &(ref ty, name) in &func.proto.params {
+        let _ = syms.insert(name, ty.clone());
+    }
+    check_stmt(&func.body, funcs, &mut syms, &func.proto.ret, source, &mut diags);
+    diags
+}
+
+fn check_stmt<'input>(
+    stmt: &'input CStmt<'input>,
+    funcs: &Funcs<'input>,
+    syms: &mut Syms<'input>,
+    ret: &Option<CType>,
+    source: &'input str,
+    diags: &mut Diagnostics<'input>,
+) {
+    match *stmt {
+        CStmt::Decl(loc, ref ty, name, ref init) => {
+            if let Some(ref e) = *init {
+                if let Some(et) = check_expr(e, funcs, syms, source, diags) {
+                    if !assignable(&et, ty) {
+                        diags.push(Diagnostic::error(
+                            loc,
+                            format!("cannot initialize `{}` of type `{:?}` with `{:?}`", name, ty, et),
+                            source,
+                        ));
+                    }
+                }
+            }
+            let _ = syms.insert(name, ty.clone());
+        },
+
+        CStmt::Assign(loc, name, ref index, ref rhs) => {
+            let rhs_ty = check_expr(rhs, funcs, syms, source, diags);
+
+            match syms.lookup(name).cloned() {
+                Some(CType::Ref(elem_ty)) => {
+                    match *index {
+                        Some(ref idx) => {
+                            if let Some(idx_ty) = check_expr(idx, funcs, syms, source, diags) {
+                                if !is_integral(&idx_ty) {
+                                    diags.push(Diagnostic::error(
+                                        loc,
+                                        format!("array index must be integral, found `{:?}`", idx_ty),
+                                        source,
+                                    ));
+                                }
+                            }
+                        },
+                        None => diags.push(Diagnostic::error(
+                            loc,
+                            format!("`{}` is an array and must be assigned through an index", name),
+                            source,
+                        )),
+                    }
+
+                    if let Some(rhs_ty) = rhs_ty {
+                        if !assignable(&rhs_ty, &elem_ty) {
+                            diags.push(Diagnostic::error(
+                                loc,
+                                format!("cannot assign `{:?}` to `{}` of type `{:?}`", rhs_ty, name, elem_ty),
+                                source,
+                            ));
+                        }
+                    }
+                },
+
+                Some(ref lhs_ty) => {
+                    if index.is_some() {
+                        diags.push(Diagnostic::error(loc, format!("`{}` is not indexable", name), source));
+                    }
+
+                    if let Some(rhs_ty) = rhs_ty {
+                        if !assignable(&rhs_ty, lhs_ty) {
+                            diags.push(Diagnostic::error(
+                                loc,
+                                format!("cannot assign `{:?}` to `{}` of type `{:?}`", rhs_ty, name, lhs_ty),
+                                source,
+                            ));
+                        }
+                    }
+                },
+
+                None => diags.push(Diagnostic::error(loc, format!("undeclared identifier `{}`", name), source)),
+            }
+        },
+
+        CStmt::Call(loc, name, ref args) => {
+            check_call(loc, name, args, funcs, syms, source, diags);
+        },
+
+        CStmt::Return(loc, ref val) => {
+            let val_ty = val.as_ref().and_then(|e| check_expr(e, funcs, syms, source, diags));
+
+            match (ret, val_ty) {
+                (None, None) => (),
+                (None, Some(t)) => diags.push(Diagnostic::error(
+                    loc,
+                    format!("function does not return a value, found `{:?}`", t),
+                    source,
+                )),
+                (Some(expected), Some(t)) => {
+                    if !assignable(&t, expected) {
+                        diags.push(Diagnostic::error(
+                            loc,
+                            format!("expected return type `{:?}`, found `{:?}`", expected, t),
+                            source,
+                        ));
+                    }
+                },
+                (Some(expected), None) => {
+                    if val.is_none() {
+                        diags.push(Diagnostic::error(
+                            loc,
+                            format!("expected a return value of type `{:?}`", expected),
+                            source,
+                        ));
+                    }
+                },
+            }
+        },
+
+        CStmt::Block(_, ref stmts) => {
+            syms.push_frame();
+            for s in stmts {
+                check_stmt(s, funcs, syms, ret, source, diags);
+            }
+            syms.pop_frame();
+        },
+
+        CStmt::If(loc, ref cond, ref then_branch, ref else_branch) => {
+            check_condition(cond, loc, funcs, syms, source, diags);
+            check_stmt(then_branch, funcs, syms, ret, source, diags);
+            if let Some(ref else_branch) = *else_branch {
+                check_stmt(else_branch, funcs, syms, ret, source, diags);
+            }
+        },
+
+        CStmt::While(loc, ref cond, ref body) => {
+            check_condition(cond, loc, funcs, syms, source, diags);
+            check_stmt(body, funcs, syms, ret, source, diags);
+        },
+
+        CStmt::Print(_, _, ref e) => {
+            check_expr(e, funcs, syms, source, diags);
+        },
+
+        CStmt::Error => (),
+    }
+}
+
+fn check_condition<'input>(
+    cond: &'input CExpr<'input>,
+    loc: CLoc,
+    funcs: &Funcs<'input>,
+    syms: &mut Syms<'input>,
+    source: &'input str,
+    diags: &mut Diagnostics<'input>,
+) {
+    if let Some(ty) = check_expr(cond, funcs, syms, source, diags) {
+        if !is_integral(&ty) {
+            diags.push(Diagnostic::error(loc, format!("condition must be integral, found `{:?}`", ty), source));
+        }
+    }
+}
+
+/// Computes the type of `expr`, or `None` if it is ill-typed (an error has
+/// already been pushed in that case, so callers should not report again).
+fn check_expr<'input>(
+    expr: &'input CExpr<'input>,
+    funcs: &Funcs<'input>,
+    syms: &mut Syms<'input>,
+    source: &'input str,
+    diags: &mut Diagnostics<'input>,
+) -> Option<CType> {
+    match *expr {
+        CExpr::Int(..) => Some(CType::Int),
+        CExpr::Float(..) => Some(CType::Float),
+        CExpr::Char(..) => Some(CType::Char),
+        CExpr::Str(..) => Some(CType::Ref(Box::new(CType::Char))),
+
+        CExpr::Ident(loc, name) => match syms.lookup(name) {
+            Some(ty) => Some(ty.clone()),
+            None => {
+                diags.push(Diagnostic::error(loc, format!("undeclared identifier `{}`", name), source));
+                None
+            },
+        },
+
+        CExpr::UnOp(loc, op, ref e) => {
+            let ty = check_expr(e, funcs, syms, source, diags)?;
+            match op {
+                COp::Neg if is_numeric(&ty) => Some(ty),
+                COp::Neg => {
+                    diags.push(Diagnostic::error(loc, format!("cannot negate `{:?}`", ty), source));
+                    None
+                },
+                COp::Not if is_integral(&ty) => Some(CType::Int),
+                COp::Not => {
+                    diags.push(Diagnostic::error(loc, format!("`!` requires an integral operand, found `{:?}`", ty), source));
+                    None
+                },
+                _ => unreachable!("not a unary operator"),
+            }
+        },
+
+        CExpr::BinOp(loc, op, ref l, ref r) => {
+            let l_ty = check_expr(l, funcs, syms, source, diags);
+            let r_ty = check_expr(r, funcs, syms, source, diags);
+            let (l_ty, r_ty) = match (l_ty, r_ty) {
+                (Some(l_ty), Some(r_ty)) => (l_ty, r_ty),
+                _ => return None,
+            };
+
+            match op {
+                COp::Mul | COp::Div | COp::Add | COp::Sub => {
+                    if is_numeric(&l_ty) && is_numeric(&r_ty) {
+                        Some(promote(&l_ty, &r_ty))
+                    } else {
+                        diags.push(Diagnostic::error(
+                            loc,
+                            format!("arithmetic `{:?}` requires numeric operands, found `{:?}` and `{:?}`", op, l_ty, r_ty),
+                            source,
+                        ));
+                        None
+                    }
+                },
+                COp::Eq | COp::Neq | COp::Lt | COp::Lte | COp::Gt | COp::Gte | COp::And | COp::Or => {
+                    Some(CType::Int)
+                },
+                _ => unreachable!("not a binary operator"),
+            }
+        },
+
+        CExpr::Index(loc, name, ref index) => {
+            let index_ty = check_expr(index, funcs, syms, source, diags);
+
+            match syms.lookup(name).cloned() {
+                Some(CType::Ref(elem_ty)) => {
+                    if let Some(index_ty) = index_ty {
+                        if !is_integral(&index_ty) {
+                            diags.push(Diagnostic::error(
+                                loc,
+                                format!("array index must be integral, found `{:?}`", index_ty),
+                                source,
+                            ));
+                            return None;
+                        }
+                    }
+                    Some(*elem_ty)
+                },
+                Some(other) => {
+                    diags.push(Diagnostic::error(loc, format!("`{}` is not indexable (found `{:?}`)", name, other), source));
+                    None
+                },
+                None => {
+                    diags.push(Diagnostic::error(loc, format!("undeclared identifier `{}`", name), source));
+                    None
+                },
+            }
+        },
+
+        CExpr::Call(loc, name, ref args) => {
+            let ty = check_call(loc, name, args, funcs, syms, source, diags);
+            if ty.is_none() && funcs.lookup(name).is_some() {
+                diags.push(Diagnostic::error(loc, format!("`{}` does not return a value", name), source));
+            }
+            ty
+        },
+
+        CExpr::Error => None,
+    }
+}
+
+fn check_call<'input>(
+    loc: CLoc,
+    name: CIdent<'input>,
+    args: &'input [Box<CExpr<'input>>],
+    funcs: &Funcs<'input>,
+    syms: &mut Syms<'input>,
+    source: &'input str,
+    diags: &mut Diagnostics<'input>,
+) -> Option<CType> {
+    let proto = match funcs.lookup(name) {
+        Some(proto) => *proto,
+        None => {
+            diags.push(Diagnostic::error(loc, format!("call to undeclared function `{}`", name), source));
+            for arg in args {
+                check_expr(arg, funcs, syms, source, diags);
+            }
+            return None;
+        },
+    };
+
+    if args.len() != proto.params.len() {
+        diags.push(Diagnostic::error(
+            loc,
+            format!("`{}` expects {} argument(s), found {}", name, proto.params.len(), args.len()),
+            source,
+        ));
+    }
+
+    for (i, arg) in args.iter().enumerate() {
+        let arg_ty = check_expr(arg, funcs, syms, source, diags);
+        if let (Some(arg_ty), Some(&(ref param_ty, _))) = (arg_ty, proto.params.get(i)) {
+            if !assignable(&arg_ty, param_ty) {
+                diags.push(Diagnostic::error(
+                    loc,
+                    format!("argument {} to `{}` expects `{:?}`, found `{:?}`", i + 1, name, param_ty, arg_ty),
+                    source,
+                ));
+            }
+        }
+    }
+
+    proto.ret.clone()
+}
+
+/// Promotion rank used to pick the "wider" type for a binary arithmetic
+/// result: `Char < Int < Float`. `None` for non-numeric types.
+fn rank(ty: &CType) -> Option<u8> {
+    match *ty {
+        CType::Char => Some(0),
+        CType::Int => Some(1),
+        CType::Float => Some(2),
+        CType::Ref(_) => None,
+    }
+}
+
+fn is_numeric(ty: &CType) -> bool {
+    rank(ty).is_some()
+}
+
+fn is_integral(ty: &CType) -> bool {
+    match *ty {
+        CType::Char | CType::Int => true,
+        _ => false,
+    }
+}
+
+fn promote(a: &CType, b: &CType) -> CType {
+    match (rank(a), rank(b)) {
+        (Some(ra), Some(rb)) if rb > ra => b.clone(),
+        _ => a.clone(),
+    }
+}
+
+/// Whether a value of type `from` may be used where `to` is expected:
+/// numeric types convert implicitly among each other, everything else must
+/// match exactly.
+fn assignable(from: &CType, to: &CType) -> bool {
+    (is_numeric(from) && is_numeric(to)) || from == to
+}
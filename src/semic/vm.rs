@@ -0,0 +1,431 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use super::interp::{render_format, Value};
+
+// stack virtual machine
+//
+// Executes the linear instruction stream produced by `codegen::compile_prog`.
+// Every activation record gets its own locals array (addressed by frame
+// offset); all frames share one operand stack, in the usual stack-machine
+// style. This is the "compiled" counterpart to `interp::run_prog`'s
+// tree-walking evaluation.
+
+#[derive(Clone, Debug)]
+pub enum Instr {
+    PushInt(i32),
+    PushFloat(f32),
+    PushStr(String),
+
+    Load(usize),
+    Store(usize),
+    LoadGlobal(usize),
+    StoreGlobal(usize),
+
+    /// Converts the top-of-stack value to the given representation —
+    /// emitted ahead of every `Store`/`StoreGlobal` (and after every
+    /// argument of a `Call`) whose target has a non-array declared type,
+    /// whether or not the value already matches it, e.g. `float x = 7;`
+    /// needs its `Int(7)` turned into `Float(7.0)` before it's stored so
+    /// later arithmetic on `x` takes the float path.
+    CoerceInt,
+    CoerceFloat,
+    CoerceChar,
+
+    AddInt,
+    SubInt,
+    MulInt,
+    DivInt,
+    AddFloat,
+    SubFloat,
+    MulFloat,
+    DivFloat,
+
+    CmpEq,
+    CmpNeq,
+    CmpLt,
+    CmpLte,
+    CmpGt,
+    CmpGte,
+
+    Neg,
+    Not,
+    And,
+    Or,
+
+    Index,
+    StoreIndex,
+
+    Jump(usize),
+    JumpUnless(usize),
+
+    Call(usize),
+    Ret,
+
+    ExternBuiltin(String),
+
+    Pop,
+}
+
+impl fmt::Display for Instr {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instr::PushInt(n) => write!(fmt, "PushInt {}", n),
+            Instr::PushFloat(f) => write!(fmt, "PushFloat {}", f),
+            Instr::PushStr(ref s) => write!(fmt, "PushStr {:?}", s),
+            Instr::Load(slot) => write!(fmt, "Load {}", slot),
+            Instr::Store(slot) => write!(fmt, "Store {}", slot),
+            Instr::LoadGlobal(slot) => write!(fmt, "LoadGlobal {}", slot),
+            Instr::StoreGlobal(slot) => write!(fmt, "StoreGlobal {}", slot),
+            Instr::CoerceInt => write!(fmt, "CoerceInt"),
+            Instr::CoerceFloat => write!(fmt, "CoerceFloat"),
+            Instr::CoerceChar => write!(fmt, "CoerceChar"),
+            Instr::AddInt => write!(fmt, "AddInt"),
+            Instr::SubInt => write!(fmt, "SubInt"),
+            Instr::MulInt => write!(fmt, "MulInt"),
+            Instr::DivInt => write!(fmt, "DivInt"),
+            Instr::AddFloat => write!(fmt, "AddFloat"),
+            Instr::SubFloat => write!(fmt, "SubFloat"),
+            Instr::MulFloat => write!(fmt, "MulFloat"),
+            Instr::DivFloat => write!(fmt, "DivFloat"),
+            Instr::CmpEq => write!(fmt, "CmpEq"),
+            Instr::CmpNeq => write!(fmt, "CmpNeq"),
+            Instr::CmpLt => write!(fmt, "CmpLt"),
+            Instr::CmpLte => write!(fmt, "CmpLte"),
+            Instr::CmpGt => write!(fmt, "CmpGt"),
+            Instr::CmpGte => write!(fmt, "CmpGte"),
+            Instr::Neg => write!(fmt, "Neg"),
+            Instr::Not => write!(fmt, "Not"),
+            Instr::And => write!(fmt, "And"),
+            Instr::Or => write!(fmt, "Or"),
+            Instr::Index => write!(fmt, "Index"),
+            Instr::StoreIndex => write!(fmt, "StoreIndex"),
+            Instr::Jump(target) => write!(fmt, "Jump {}", target),
+            Instr::JumpUnless(target) => write!(fmt, "JumpUnless {}", target),
+            Instr::Call(addr) => write!(fmt, "Call {}", addr),
+            Instr::Ret => write!(fmt, "Ret"),
+            Instr::ExternBuiltin(ref id) => write!(fmt, "ExternBuiltin {}", id),
+            Instr::Pop => write!(fmt, "Pop"),
+        }
+    }
+}
+
+/// One `CFunc`'s compiled instruction stream, labeled with its source name
+/// so `Call` targets and the disassembly can refer to it.
+pub struct Section {
+    pub name: String,
+    pub params: usize,
+    pub instrs: Vec<Instr>,
+}
+
+pub struct Program {
+    pub sections: Vec<Section>,
+    /// Instructions that evaluate every top-level `CProgElem::Decl`'s
+    /// initializer (or `0`) and `StoreGlobal` it, run once before `entry`
+    /// so functions compiled against `LoadGlobal`/`StoreGlobal` slots see
+    /// them populated.
+    pub globals: Vec<Instr>,
+    /// Number of distinct global slots `globals` initializes.
+    pub global_count: usize,
+}
+
+impl Program {
+    pub fn section_index(&self, name: &str) -> Option<usize> {
+        self.sections.iter().position(|s| s.name == name)
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        if !self.globals.is_empty() {
+            writeln!(fmt, "section[<globals>]")?;
+            for (i, instr) in self.globals.iter().enumerate() {
+                writeln!(fmt, "  {}: {}", i, instr)?;
+            }
+        }
+        for section in &self.sections {
+            writeln!(fmt, "section[{}]", section.name)?;
+            for (i, instr) in section.instrs.iter().enumerate() {
+                writeln!(fmt, "  {}: {}", i, instr)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum VmError {
+    StackUnderflow,
+    UnknownSection(String),
+    UnknownBuiltin(String),
+    DivisionByZero,
+    IndexOutOfBounds,
+    NotIndexable,
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VmError::StackUnderflow => write!(fmt, "operand stack underflow"),
+            VmError::UnknownSection(ref name) => write!(fmt, "no such section `{}`", name),
+            VmError::UnknownBuiltin(ref name) => write!(fmt, "no such builtin `{}`", name),
+            VmError::DivisionByZero => write!(fmt, "division by zero"),
+            VmError::IndexOutOfBounds => write!(fmt, "index out of bounds"),
+            VmError::NotIndexable => write!(fmt, "value is not indexable"),
+        }
+    }
+}
+
+/// `usize::MAX` is never a valid index into `Program::sections` (it would
+/// need more memory than exists to allocate that many), so it doubles as
+/// the sentinel `Frame::section` for the synthetic globals-initializer
+/// pseudo-section, which lives in `Program::globals` instead.
+const GLOBALS_SECTION: usize = usize::MAX;
+
+struct Frame {
+    section: usize,
+    pc: usize,
+    locals: Vec<Value>,
+}
+
+pub struct Vm<'p> {
+    program: &'p Program,
+}
+
+impl<'p> Vm<'p> {
+    pub fn new(program: &'p Program) -> Vm<'p> {
+        Vm { program }
+    }
+
+    fn section_instrs(&self, section: usize) -> &[Instr] {
+        if section == GLOBALS_SECTION {
+            &self.program.globals
+        } else {
+            &self.program.sections[section].instrs
+        }
+    }
+
+    /// Runs the section named `entry` to completion, returning the value it
+    /// `Ret`s with. Global variables are initialized first, against their
+    /// own frame and operand stack, so their `StoreGlobal`s land in
+    /// `globals` before `entry` can `LoadGlobal` them.
+    pub fn run(&self, entry: &str) -> Result<Value, VmError> {
+        let entry_idx = self
+            .program
+            .section_index(entry)
+            .ok_or_else(|| VmError::UnknownSection(entry.to_string()))?;
+
+        let mut globals = vec![Value::Int(0); self.program.global_count];
+
+        if !self.program.globals.is_empty() {
+            let mut init_frames = vec![Frame { section: GLOBALS_SECTION, pc: 0, locals: Vec::new() }];
+            let mut init_stack = Vec::new();
+            self.exec(&mut init_frames, &mut init_stack, &mut globals)?;
+        }
+
+        let mut frames = vec![Frame { section: entry_idx, pc: 0, locals: Vec::new() }];
+        let mut stack = Vec::new();
+        self.exec(&mut frames, &mut stack, &mut globals)
+    }
+
+    /// Runs `frames` (topmost first) to completion, returning the value the
+    /// outermost frame `Ret`s with.
+    fn exec(&self, frames: &mut Vec<Frame>, stack: &mut Vec<Value>, globals: &mut Vec<Value>) -> Result<Value, VmError> {
+        loop {
+            let (section, pc) = {
+                let frame = frames.last().expect("call stack never empties while running");
+                (frame.section, frame.pc)
+            };
+            let instr = self.section_instrs(section)[pc].clone();
+            frames.last_mut().unwrap().pc += 1;
+
+            match instr {
+                Instr::PushInt(n) => stack.push(Value::Int(n)),
+                Instr::PushFloat(f) => stack.push(Value::Float(f)),
+                Instr::PushStr(s) => stack.push(str_to_value(&s)),
+
+                Instr::Load(slot) => {
+                    let frame = frames.last().unwrap();
+                    let val = frame.locals.get(slot).cloned().unwrap_or(Value::Int(0));
+                    stack.push(val);
+                },
+                Instr::Store(slot) => {
+                    let val = pop(stack)?;
+                    let frame = frames.last_mut().unwrap();
+                    if slot >= frame.locals.len() {
+                        frame.locals.resize(slot + 1, Value::Int(0));
+                    }
+                    frame.locals[slot] = val;
+                },
+
+                Instr::LoadGlobal(slot) => {
+                    let val = globals.get(slot).cloned().unwrap_or(Value::Int(0));
+                    stack.push(val);
+                },
+                Instr::CoerceInt => {
+                    let val = pop(stack)?;
+                    stack.push(Value::Int(val.as_i32()));
+                },
+                Instr::CoerceFloat => {
+                    let val = pop(stack)?;
+                    stack.push(Value::Float(val.as_f32()));
+                },
+                Instr::CoerceChar => {
+                    let val = pop(stack)?;
+                    stack.push(Value::Char((val.as_i32() as u8) as char));
+                },
+
+                Instr::StoreGlobal(slot) => {
+                    let val = pop(stack)?;
+                    if slot >= globals.len() {
+                        globals.resize(slot + 1, Value::Int(0));
+                    }
+                    globals[slot] = val;
+                },
+
+                Instr::AddInt => { let (a, b) = pop2(stack)?; stack.push(Value::Int(a.as_i32() + b.as_i32())); },
+                Instr::SubInt => { let (a, b) = pop2(stack)?; stack.push(Value::Int(a.as_i32() - b.as_i32())); },
+                Instr::MulInt => { let (a, b) = pop2(stack)?; stack.push(Value::Int(a.as_i32() * b.as_i32())); },
+                Instr::DivInt => {
+                    let (a, b) = pop2(stack)?;
+                    let divisor = b.as_i32();
+                    if divisor == 0 {
+                        return Err(VmError::DivisionByZero);
+                    }
+                    stack.push(Value::Int(a.as_i32() / divisor));
+                },
+
+                Instr::AddFloat => { let (a, b) = pop2(stack)?; stack.push(Value::Float(a.as_f32() + b.as_f32())); },
+                Instr::SubFloat => { let (a, b) = pop2(stack)?; stack.push(Value::Float(a.as_f32() - b.as_f32())); },
+                Instr::MulFloat => { let (a, b) = pop2(stack)?; stack.push(Value::Float(a.as_f32() * b.as_f32())); },
+                Instr::DivFloat => {
+                    let (a, b) = pop2(stack)?;
+                    let divisor = b.as_f32();
+                    if divisor == 0.0 {
+                        return Err(VmError::DivisionByZero);
+                    }
+                    stack.push(Value::Float(a.as_f32() / divisor));
+                },
+
+                Instr::CmpEq => { let (a, b) = pop2(stack)?; stack.push(Value::Int((a.as_f32() == b.as_f32()) as i32)); },
+                Instr::CmpNeq => { let (a, b) = pop2(stack)?; stack.push(Value::Int((a.as_f32() != b.as_f32()) as i32)); },
+                Instr::CmpLt => { let (a, b) = pop2(stack)?; stack.push(Value::Int((a.as_f32() < b.as_f32()) as i32)); },
+                Instr::CmpLte => { let (a, b) = pop2(stack)?; stack.push(Value::Int((a.as_f32() <= b.as_f32()) as i32)); },
+                Instr::CmpGt => { let (a, b) = pop2(stack)?; stack.push(Value::Int((a.as_f32() > b.as_f32()) as i32)); },
+                Instr::CmpGte => { let (a, b) = pop2(stack)?; stack.push(Value::Int((a.as_f32() >= b.as_f32()) as i32)); },
+
+                Instr::Neg => {
+                    let a = pop(stack)?;
+                    stack.push(match a {
+                        Value::Float(f) => Value::Float(-f),
+                        other => Value::Int(-other.as_i32()),
+                    });
+                },
+                Instr::Not => {
+                    let a = pop(stack)?;
+                    stack.push(Value::Int(if a.truthy() { 0 } else { 1 }));
+                },
+                Instr::And => { let (a, b) = pop2(stack)?; stack.push(Value::Int((a.truthy() && b.truthy()) as i32)); },
+                Instr::Or => { let (a, b) = pop2(stack)?; stack.push(Value::Int((a.truthy() || b.truthy()) as i32)); },
+
+                Instr::Index => {
+                    let (arr, idx) = pop2(stack)?;
+                    let idx = idx.as_i32();
+                    match arr {
+                        Value::Ref(cells) => {
+                            let cells = cells.borrow();
+                            if idx < 0 || idx as usize >= cells.len() {
+                                return Err(VmError::IndexOutOfBounds);
+                            }
+                            stack.push(cells[idx as usize].clone());
+                        },
+                        _ => return Err(VmError::NotIndexable),
+                    }
+                },
+                Instr::StoreIndex => {
+                    let val = pop(stack)?;
+                    let idx = pop(stack)?.as_i32();
+                    let arr = pop(stack)?;
+                    match arr {
+                        Value::Ref(cells) => {
+                            if idx < 0 {
+                                return Err(VmError::IndexOutOfBounds);
+                            }
+                            let mut cells = cells.borrow_mut();
+                            let idx = idx as usize;
+                            if idx >= cells.len() {
+                                cells.resize(idx + 1, Value::Int(0));
+                            }
+                            cells[idx] = val;
+                        },
+                        _ => return Err(VmError::NotIndexable),
+                    }
+                },
+
+                Instr::Jump(target) => {
+                    frames.last_mut().unwrap().pc = target;
+                },
+                Instr::JumpUnless(target) => {
+                    let cond = pop(stack)?;
+                    if !cond.truthy() {
+                        frames.last_mut().unwrap().pc = target;
+                    }
+                },
+
+                Instr::Call(addr) => {
+                    let params = self.program.sections[addr].params;
+                    let mut locals = Vec::with_capacity(params);
+                    for _ in 0..params {
+                        locals.push(pop(stack)?);
+                    }
+                    locals.reverse();
+                    frames.push(Frame { section: addr, pc: 0, locals });
+                },
+                Instr::Ret => {
+                    frames.pop();
+                    if frames.is_empty() {
+                        return Ok(stack.pop().unwrap_or(Value::Int(0)));
+                    }
+                },
+
+                Instr::ExternBuiltin(ref id) => call_builtin(id, stack)?,
+
+                Instr::Pop => { pop(stack)?; },
+            }
+        }
+    }
+}
+
+fn pop(stack: &mut Vec<Value>) -> Result<Value, VmError> {
+    stack.pop().ok_or(VmError::StackUnderflow)
+}
+
+fn pop2(stack: &mut Vec<Value>) -> Result<(Value, Value), VmError> {
+    let b = pop(stack)?;
+    let a = pop(stack)?;
+    Ok((a, b))
+}
+
+fn str_to_value(s: &str) -> Value {
+    Value::Ref(Rc::new(RefCell::new(s.chars().map(Value::Char).collect())))
+}
+
+fn call_builtin(id: &str, stack: &mut Vec<Value>) -> Result<(), VmError> {
+    match id {
+        "printf" => {
+            let val = pop(stack)?;
+            let format = pop(stack)?.to_string();
+            print!("{}", render_format(&format, &val));
+            stack.push(Value::Int(0));
+            Ok(())
+        },
+        _ => Err(VmError::UnknownBuiltin(id.to_string())),
+    }
+}
+
+/// Convenience wrapper mirroring `interp::run_prog`: compiles nothing itself,
+/// just runs an already-compiled `Program`'s `main` section to completion.
+pub fn run_prog(program: &Program) -> Result<i32, VmError> {
+    Vm::new(program).run("main").map(|v| v.as_i32())
+}